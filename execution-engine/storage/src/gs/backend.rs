@@ -0,0 +1,19 @@
+use common::key::Key;
+use error::Error;
+use std::path::Path;
+
+/// The storage engine underneath `RkvGs`, so `GlobalState`/`DbReader`/
+/// `TrackingCopy` callers don't need to know which one they're talking to.
+pub trait GlobalStateBackend: Sized {
+    /// Open (or create) a store rooted at `path`.
+    fn open(path: &Path) -> Result<Self, Error>;
+
+    /// Look up the raw, already-serialized bytes stored under `key`, if any.
+    fn read(&self, key: &Key) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Apply `batch` as a single transaction: every write in it lands
+    /// together, or (on any failure) none do.
+    fn write<I>(&self, batch: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = (Key, Vec<u8>)>;
+}
@@ -2,22 +2,31 @@ use common::bytesrepr::{deserialize, ToBytes};
 use common::key::Key;
 use common::value::Value;
 use error::Error;
+use gs::backend::GlobalStateBackend;
 use gs::DbReader;
 use gs::{GlobalState, TrackingCopy};
+use ouroboros::self_referencing;
 use rkv::store::single::SingleStore;
-use rkv::{Manager, Rkv, StoreOptions};
+use rkv::{Manager, Reader, Rkv, StoreOptions};
+use rkyv::ser::serializers::AllocSerializer;
+use rkyv::ser::Serializer;
+use rkyv::{archived_root, check_archived_root, AlignedVec, Archived, Deserialize as _};
 use std::fmt;
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use transform::Transform;
 
-pub struct LmdbGs {
+/// The `rkv`/LMDB flavor of `GlobalStateBackend`. `RkvGs`'s `read`/`write`,
+/// the `DbReader`/`GlobalState` impls and the proptest round trip are generic
+/// over any `GlobalStateBackend`; `snapshot` (and the `GsSnapshot` iteration
+/// built on it) exists only for this backend, i.e. on `LmdbGs`.
+pub struct RkvBackend {
     store: SingleStore,
     env: Arc<RwLock<Rkv>>,
 }
 
-impl LmdbGs {
-    pub fn new(p: &Path) -> Result<LmdbGs, Error> {
+impl GlobalStateBackend for RkvBackend {
+    fn open(p: &Path) -> Result<RkvBackend, Error> {
         let env = Manager::singleton()
             .write()
             .map_err(|_| Error::RkvError)
@@ -26,23 +35,18 @@ impl LmdbGs {
             r.open_single(Some("global_state"), StoreOptions::create())
                 .map_err(|e| e.into())
         })?;
-        Ok(LmdbGs { store, env })
+        Ok(RkvBackend { store, env })
     }
 
-    pub fn read(&self, k: &Key) -> Result<Value, Error> {
+    fn read(&self, k: &Key) -> Result<Option<Vec<u8>>, Error> {
         self.env
             .read()
             .map_err(|_| Error::RkvError)
             .and_then(|rkv| {
                 let r = rkv.read()?;
-                let maybe_curr = self.store.get(&r, k)?;
-
-                match maybe_curr {
-                    None => Err(Error::KeyNotFound { key: *k }),
-                    Some(rkv::Value::Blob(bytes)) => {
-                        let value = deserialize(bytes)?;
-                        Ok(value)
-                    }
+                match self.store.get(&r, k)? {
+                    None => Ok(None),
+                    Some(rkv::Value::Blob(bytes)) => Ok(Some(bytes.to_vec())),
                     //If we always store values as Blobs this case will never come
                     //up. TODO: Use other variants of rkb::Value (e.g. I64, Str)?
                     Some(_) => Err(Error::RkvError),
@@ -50,9 +54,9 @@ impl LmdbGs {
             })
     }
 
-    pub fn write<'a, I>(&self, mut kvs: I) -> Result<(), Error>
+    fn write<I>(&self, mut kvs: I) -> Result<(), Error>
     where
-        I: Iterator<Item = (Key, &'a Value)>,
+        I: Iterator<Item = (Key, Vec<u8>)>,
     {
         self.env
             .read()
@@ -60,8 +64,7 @@ impl LmdbGs {
             .and_then(|rkv| {
                 let mut w = rkv.write()?;
 
-                let result: Result<(), Error> = kvs.try_fold((), |_, (k, v)| {
-                    let bytes = v.to_bytes();
+                let result: Result<(), Error> = kvs.try_fold((), |_, (k, bytes)| {
                     let _ = self.store.put(&mut w, k, &rkv::Value::Blob(&bytes))?;
                     Ok(())
                 });
@@ -78,6 +81,85 @@ impl LmdbGs {
                 }
             })
     }
+}
+
+/// How an `RkvGs` encodes `Value`s into the bytes its backend stores. Fixed
+/// for the lifetime of a given store (set at construction), since switching
+/// modes on an existing store would make its previously written data
+/// unreadable under the new mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageMode {
+    /// The original `ToBytes`/`deserialize` round trip, used by both `write`
+    /// and `read` of a store opened in this mode.
+    ToBytes,
+    /// rkyv-serialized bytes, enabling the zero-copy `read_archived` below.
+    Rkyv,
+}
+
+/// Global state over any `GlobalStateBackend` `B`. `LmdbGs` below is this
+/// type specialized to the original `rkv`/LMDB backend; new backends (the
+/// pure-Rust `gs::safe_mode::SafeModeBackend`, `gs::rocksdb::RocksDbBackend`)
+/// plug in here without touching `DbReader`, `GlobalState` or `TrackingCopy`.
+pub struct RkvGs<B> {
+    backend: B,
+    mode: StorageMode,
+}
+
+/// Backwards-compatible alias for the original, `rkv`/LMDB-backed global
+/// state. Existing callers that construct `LmdbGs::new(path)` keep working
+/// unchanged.
+pub type LmdbGs = RkvGs<RkvBackend>;
+
+impl<B: GlobalStateBackend> RkvGs<B> {
+    pub fn new(p: &Path) -> Result<RkvGs<B>, Error> {
+        Self::new_with_mode(p, StorageMode::ToBytes)
+    }
+
+    pub fn new_with_mode(p: &Path, mode: StorageMode) -> Result<RkvGs<B>, Error> {
+        let backend = B::open(p)?;
+        Ok(RkvGs { backend, mode })
+    }
+
+    pub fn read(&self, k: &Key) -> Result<Value, Error> {
+        match self.backend.read(k)? {
+            None => Err(Error::KeyNotFound { key: *k }),
+            Some(bytes) => decode_value(self.mode, &bytes),
+        }
+    }
+
+    /// Read the bytes stored under `k` and hand back a validated archived
+    /// view, without reconstructing an owned `Value`; the stored bytes are
+    /// copied once into an aligned buffer owned by the returned handle. Only
+    /// meaningful for a store opened with `StorageMode::Rkyv`
+    /// (`new_with_mode`) — the stored bytes must actually be an rkyv
+    /// archive.
+    pub fn read_archived(&self, k: &Key) -> Result<ArchivedValueHandle, Error> {
+        let bytes = self
+            .backend
+            .read(k)?
+            .ok_or(Error::KeyNotFound { key: *k })?;
+        archived_value(&bytes)
+    }
+
+    fn encode(&self, v: &Value) -> Vec<u8> {
+        match self.mode {
+            StorageMode::ToBytes => v.to_bytes(),
+            StorageMode::Rkyv => {
+                let mut serializer = AllocSerializer::<256>::default();
+                serializer
+                    .serialize_value(v)
+                    .expect("rkyv serialization of Value is infallible");
+                serializer.into_serializer().into_inner().to_vec()
+            }
+        }
+    }
+
+    pub fn write<'a, I>(&self, kvs: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = (Key, &'a Value)>,
+    {
+        self.backend.write(kvs.map(|(k, v)| (k, self.encode(v))))
+    }
 
     pub fn write_single(&self, k: Key, v: &Value) -> Result<(), Error> {
         let iterator = std::iter::once((k, v));
@@ -85,7 +167,126 @@ impl LmdbGs {
     }
 }
 
-impl DbReader for LmdbGs {
+impl RkvGs<RkvBackend> {
+    /// Open a single `rkv` read transaction and hold it for the life of the
+    /// returned handle, so every read through it observes one frozen view of
+    /// global state no matter what commits land afterwards. This addresses
+    /// the TODO in `RkvGs`'s `DbReader::get` below: instead of each read opening and
+    /// dropping its own transaction, an executing deploy can take one
+    /// snapshot and read as many keys as it needs against a stable root
+    /// while concurrent commits proceed (the env's `RwLock` is only ever
+    /// taken in read mode here, by both readers and writers alike — the real
+    /// mutual exclusion is `rkv`'s own transaction handling).
+    ///
+    /// Carries `self.mode` along so reads through the snapshot decode the
+    /// same way `RkvGs::read` would.
+    pub fn snapshot(&self) -> Result<GsSnapshot, Error> {
+        GsSnapshotTryBuilder {
+            env: self.backend.env.clone(),
+            store: self.backend.store.clone(),
+            mode: self.mode,
+            guard_builder: |env: &Arc<RwLock<Rkv>>| env.read().map_err(|_| Error::RkvError),
+            reader_builder: |guard: &RwLockReadGuard<Rkv>| guard.read().map_err(|e| e.into()),
+        }
+        .try_build()
+    }
+}
+
+/// A single, frozen view of global state backed by one long-lived `rkv`
+/// read transaction. See `RkvGs::snapshot`.
+///
+/// `reader` borrows from `guard`, which borrows from `env`; `ouroboros`
+/// generates the self-referential plumbing (in place of a hand-rolled
+/// `transmute` to `'static`) and ties `reader`'s drop to `guard`'s, so the
+/// borrow can never outlive what it points into.
+#[self_referencing]
+pub struct GsSnapshot {
+    env: Arc<RwLock<Rkv>>,
+    store: SingleStore,
+    mode: StorageMode,
+    #[borrows(env)]
+    #[covariant]
+    guard: RwLockReadGuard<'this, Rkv>,
+    #[borrows(guard)]
+    #[covariant]
+    reader: Reader<'this>,
+}
+
+impl DbReader for GsSnapshot {
+    fn get(&self, k: &Key) -> Result<Value, Error> {
+        match self.borrow_store().get(self.borrow_reader(), k)? {
+            None => Err(Error::KeyNotFound { key: *k }),
+            Some(rkv::Value::Blob(bytes)) => decode_value(*self.borrow_mode(), bytes),
+            Some(_) => Err(Error::RkvError),
+        }
+    }
+}
+
+impl GsSnapshot {
+    /// Iterate every `(Key, Value)` pair in the store, in byte-serialized
+    /// key order (i.e. the order of `Key::to_bytes()`, which is how `rkv`'s
+    /// cursor orders entries — this is *not* necessarily a meaningful
+    /// application-level ordering of `Key` variants).
+    pub fn iter_all(&self) -> Result<impl Iterator<Item = Result<(Key, Value), Error>> + '_, Error> {
+        let mode = *self.borrow_mode();
+        let iter = self.borrow_store().iter_start(self.borrow_reader())?;
+        Ok(iter.map(move |entry| decode_entry(mode, entry)))
+    }
+
+    /// Like `iter_all`, but starting at the first key whose serialized bytes
+    /// are greater than or equal to `start`'s; keys ordered before `start`
+    /// are skipped.
+    pub fn iter_from(
+        &self,
+        start: &Key,
+    ) -> Result<impl Iterator<Item = Result<(Key, Value), Error>> + '_, Error> {
+        let mode = *self.borrow_mode();
+        let iter = self.borrow_store().iter_from(self.borrow_reader(), start)?;
+        Ok(iter.map(move |entry| decode_entry(mode, entry)))
+    }
+}
+
+fn decode_entry(
+    mode: StorageMode,
+    entry: Result<(&[u8], Option<rkv::Value>), rkv::StoreError>,
+) -> Result<(Key, Value), Error> {
+    let (key_bytes, maybe_value) = entry?;
+    let key: Key = deserialize(key_bytes)?;
+    match maybe_value {
+        Some(rkv::Value::Blob(bytes)) => {
+            let value = decode_value(mode, bytes)?;
+            Ok((key, value))
+        }
+        _ => Err(Error::RkvError),
+    }
+}
+
+/// Decode bytes written by `RkvGs::encode` under `mode`, taking the same
+/// branch `encode` took when it produced them. Shared by `RkvGs::read` and
+/// `GsSnapshot`'s reads/iteration, both of which carry the store's `mode`
+/// alongside the bytes they decode.
+fn decode_value(mode: StorageMode, bytes: &[u8]) -> Result<Value, Error> {
+    match mode {
+        StorageMode::ToBytes => deserialize(bytes),
+        StorageMode::Rkyv => Ok(from_archived(archived_value(bytes)?.get())),
+    }
+}
+
+/// `rkv` hands back an arbitrary, unaligned `&[u8]`; rkyv's archived types
+/// require their backing bytes to be aligned, so we copy into an
+/// `AlignedVec` rather than assume the backend's buffer happens to already
+/// be aligned the way `Value`'s archive needs. Also validates (via
+/// `bytecheck`) before handing out a reference into the bytes, so a corrupt
+/// store, or one still holding `ToBytes` data, can't be read as a bogus
+/// `Archived<Value>`.
+fn archived_value(bytes: &[u8]) -> Result<ArchivedValueHandle, Error> {
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    check_archived_root::<Value>(&aligned).map_err(|_| Error::RkvError)?;
+    Ok(ArchivedValueHandle { bytes: aligned })
+}
+
+impl<B: GlobalStateBackend> DbReader for RkvGs<B> {
     fn get(&self, k: &Key) -> Result<Value, Error> {
         //TODO: The `Reader` should really be static for the DbReader instance,
         //i.e. just by creating a DbReader for LMDB it should create a `Reader`
@@ -95,7 +296,7 @@ impl DbReader for LmdbGs {
     }
 }
 
-impl GlobalState for LmdbGs {
+impl<B: GlobalStateBackend> GlobalState for RkvGs<B> {
     fn apply(&mut self, k: Key, t: Transform) -> Result<(), Error> {
         let maybe_curr = self.get(&k);
         match maybe_curr {
@@ -115,16 +316,44 @@ impl GlobalState for LmdbGs {
     }
 }
 
+/// A validated, zero-copy handle onto an rkyv-archived `Value`, returned by
+/// `RkvGs::read_archived`.
+pub struct ArchivedValueHandle {
+    bytes: AlignedVec,
+}
+
+impl ArchivedValueHandle {
+    pub fn get(&self) -> &Archived<Value> {
+        // SAFETY: `self.bytes` was validated by `check_archived_root` in
+        // `archived_value`, the only place this handle is constructed.
+        unsafe { archived_root::<Value>(&self.bytes) }
+    }
+}
+
+/// Materialize an owned `Value` from a zero-copy archived view, for callers
+/// that need to mutate it or hold it past the handle's lifetime.
+pub fn from_archived(archived: &Archived<Value>) -> Value {
+    archived
+        .deserialize(&mut rkyv::Infallible)
+        .expect("Value's rkyv archive deserializes infallibly")
+}
+
 impl fmt::Debug for LmdbGs {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "LMDB({:?})", self.env)
+        write!(f, "LMDB({:?})", self.backend.env)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use common::bytesrepr::ToBytes;
+    use common::key::Key;
     use gens::gens::*;
-    use gs::lmdb::LmdbGs;
+    use gs::lmdb::{LmdbGs, RkvBackend, RkvGs};
+    use gs::safe_mode::SafeModeBackend;
+    use gs::test_support::rw_roundtrip;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
     use tempfile::tempdir;
 
     #[test]
@@ -140,15 +369,172 @@ mod tests {
 
     #[test]
     fn lmdb_rw() {
+        rw_roundtrip::<RkvBackend>();
+    }
+
+    #[test]
+    fn safe_mode_rw() {
+        rw_roundtrip::<SafeModeBackend>();
+    }
+
+    #[test]
+    fn rkyv_read_archived_matches_owned_read() {
+        use gs::lmdb::{from_archived, StorageMode};
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+        let lmdb = RkvGs::<RkvBackend>::new_with_mode(&path, StorageMode::Rkyv).unwrap();
+
+        proptest!(|(k in key_arb(), v in value_arb())| {
+            lmdb.write_single(k, &v).unwrap();
+            let handle = lmdb.read_archived(&k).unwrap();
+            prop_assert_eq!(from_archived(handle.get()), v);
+        });
+    }
+
+    #[test]
+    fn rkyv_mode_apply_survives_a_second_write() {
+        use gs::GlobalState;
+        use gs::lmdb::StorageMode;
+        use transform::Transform;
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+        let mut lmdb = RkvGs::<RkvBackend>::new_with_mode(&path, StorageMode::Rkyv).unwrap();
+
+        proptest!(|(k in key_arb(), v1 in value_arb(), v2 in value_arb())| {
+            // For a new key the first `apply`'s `self.get` finds nothing and
+            // returns `KeyNotFound` before any decoding; the second one's
+            // `self.get` decodes the bytes the first wrote, which is exactly
+            // the path that used to try to `ToBytes`-decode rkyv bytes and fail.
+            lmdb.apply(k, Transform::Write(v1)).unwrap();
+            lmdb.apply(k, Transform::Write(v2.clone())).unwrap();
+            prop_assert_eq!(lmdb.read(&k), Ok(v2));
+        });
+    }
+
+    #[test]
+    fn snapshot_sees_stable_root() {
+        use gs::DbReader;
+
         let temp_dir = tempdir().unwrap();
         let path = temp_dir.path();
         let lmdb = LmdbGs::new(&path).unwrap();
 
+        proptest!(|(k in key_arb(), v1 in value_arb(), v2 in value_arb())| {
+            lmdb.write_single(k, &v1).unwrap();
+            let snapshot = lmdb.snapshot().unwrap();
+
+            // A write after the snapshot was taken must not be visible
+            // through it.
+            lmdb.write_single(k, &v2).unwrap();
+
+            prop_assert_eq!(snapshot.get(&k), Ok(v1.clone()));
+            prop_assert_eq!(lmdb.read(&k), Ok(v2.clone()));
+        });
+    }
+
+    /// `GsSnapshot` is self-referential (`reader` borrows from `guard`,
+    /// which borrows from `env`); `ouroboros` generates the drop order for
+    /// us, but this pins down the ordering we actually rely on: a snapshot
+    /// must stay readable after a sibling snapshot, and the `RkvGs` that
+    /// created both, have already been dropped. Worth re-running under
+    /// `cargo +nightly miri test snapshot_outlives` to double-check the
+    /// generated unsafe impl, since `miri` isn't available in every
+    /// environment this crate is built in.
+    #[test]
+    fn snapshot_outlives_its_source_and_sibling_snapshots() {
+        use gs::DbReader;
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+
         proptest!(|(k in key_arb(), v in value_arb())| {
-          let write = lmdb.write_single(k, &v);
-          let read = lmdb.read(&k);
-          assert_matches!(write, Ok(_));
-          prop_assert_eq!(read, Ok(v.clone()));
+            let lmdb = LmdbGs::new(&path).unwrap();
+            lmdb.write_single(k, &v).unwrap();
+
+            let snapshot = lmdb.snapshot().unwrap();
+            let sibling = lmdb.snapshot().unwrap();
+            // Drop order deliberately scrambled: the sibling snapshot, then
+            // the `RkvGs` both were taken from, before `snapshot` is read.
+            drop(sibling);
+            drop(lmdb);
+
+            prop_assert_eq!(snapshot.get(&k), Ok(v));
+        });
+    }
+
+    #[test]
+    fn snapshot_and_iteration_decode_rkyv_mode_stores() {
+        use gs::DbReader;
+        use gs::lmdb::StorageMode;
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+        let lmdb = RkvGs::<RkvBackend>::new_with_mode(&path, StorageMode::Rkyv).unwrap();
+        // The store is shared by every proptest case, so iteration covers
+        // every key written so far, ordered by serialized bytes.
+        let written: RefCell<BTreeMap<Vec<u8>, Key>> = RefCell::new(BTreeMap::new());
+
+        proptest!(|(ks in prop::collection::hash_set(key_arb(), 5), v in value_arb())| {
+            let mut keys: Vec<Key> = ks.into_iter().collect();
+            for k in &keys {
+                lmdb.write_single(*k, &v).unwrap();
+                written.borrow_mut().insert(k.to_bytes(), *k);
+            }
+            keys.sort_by_key(ToBytes::to_bytes);
+
+            let snapshot = lmdb.snapshot().unwrap();
+            prop_assert_eq!(snapshot.get(&keys[0]), Ok(v.clone()));
+
+            let all_keys: Vec<Key> = written.borrow().values().cloned().collect();
+            let iterated: Vec<Key> = snapshot
+                .iter_all()
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect();
+            prop_assert_eq!(&iterated, &all_keys);
         });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn iter_all_follows_serialized_key_order() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+        let lmdb = LmdbGs::new(&path).unwrap();
+        // The store is shared by every proptest case, so iteration covers
+        // every key written so far, ordered by serialized bytes.
+        let written: RefCell<BTreeMap<Vec<u8>, Key>> = RefCell::new(BTreeMap::new());
+
+        proptest!(|(ks in prop::collection::hash_set(key_arb(), 5), v in value_arb())| {
+            let mut keys: Vec<Key> = ks.into_iter().collect();
+            for k in &keys {
+                lmdb.write_single(*k, &v).unwrap();
+                written.borrow_mut().insert(k.to_bytes(), *k);
+            }
+            keys.sort_by_key(ToBytes::to_bytes);
+
+            let all_keys: Vec<Key> = written.borrow().values().cloned().collect();
+            let snapshot = lmdb.snapshot().unwrap();
+            let iterated: Vec<Key> = snapshot
+                .iter_all()
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect();
+            prop_assert_eq!(&iterated, &all_keys);
+
+            let skip_from = keys[2];
+            let expected_from: Vec<Key> = written
+                .borrow()
+                .range(skip_from.to_bytes()..)
+                .map(|(_, k)| *k)
+                .collect();
+            let from_iterated: Vec<Key> = snapshot
+                .iter_from(&skip_from)
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect();
+            prop_assert_eq!(from_iterated, expected_from);
+        });
+    }
+}
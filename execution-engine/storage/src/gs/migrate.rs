@@ -0,0 +1,182 @@
+//! A backend migration tool, analogous to `rkv`'s own `arch_migrator`: a
+//! supported path for moving a store between architectures (32- vs 64-bit)
+//! or between backends, none of which share an on-disk format.
+
+use common::key::Key;
+use common::value::Value;
+use error::Error;
+use gs::backend::GlobalStateBackend;
+use gs::lmdb::{RkvGs, StorageMode};
+use std::path::Path;
+
+/// Number of `(Key, Value)` pairs committed per destination transaction, so
+/// migrating a multi-gigabyte store doesn't require one giant transaction.
+const BATCH_SIZE: usize = 1_000;
+
+/// Copy every key in the store at `src` into `dst`, batching commits along
+/// the way. `src` is always opened as the `rkv`/LMDB store, since that's the
+/// only format operators currently have on disk to migrate *from*; `dst` can
+/// be any `GlobalStateBackend` (including another `LmdbGs`, e.g. to recover
+/// a store written on a different architecture).
+///
+/// `src_mode` is `src`'s own `StorageMode` — it has to be passed in rather
+/// than detected, since a store's bytes don't say how they were encoded.
+/// Passing the wrong one decodes garbage (`ToBytes` against an `Rkyv` store)
+/// or fails outright (`Rkyv` against a `ToBytes` store); there's no way to
+/// catch a mismatch from here.
+///
+/// Writes go through `dst`'s own `write`, so `dst`'s `StorageMode` (picked
+/// when it was opened) decides how the migrated values are re-encoded —
+/// migrating *into* a `StorageMode::Rkyv` store produces a real rkyv
+/// archive, not `src`'s original bytes copied verbatim.
+///
+/// In `dry_run` mode every value is decoded (proving it decodes) but
+/// nothing is written to `dst`. Returns the number of keys migrated (or, in
+/// dry-run mode, validated).
+pub fn migrate<B: GlobalStateBackend>(
+    src: &Path,
+    src_mode: StorageMode,
+    dst: &RkvGs<B>,
+    dry_run: bool,
+) -> Result<usize, Error> {
+    let source = RkvGs::new_with_mode(src, src_mode)?;
+    let snapshot = source.snapshot()?;
+
+    let mut migrated = 0;
+    let mut batch: Vec<(Key, Value)> = Vec::with_capacity(BATCH_SIZE);
+
+    for entry in snapshot.iter_all()? {
+        let (key, value) = entry?;
+        migrated += 1;
+        if dry_run {
+            continue;
+        }
+        batch.push((key, value));
+        if batch.len() >= BATCH_SIZE {
+            dst.write(batch.iter().map(|(k, v)| (*k, v)))?;
+            batch.clear();
+        }
+    }
+    if !dry_run && !batch.is_empty() {
+        dst.write(batch.iter().map(|(k, v)| (*k, v)))?;
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::key::Key;
+    use error::Error;
+    use gens::gens::*;
+    use gs::lmdb::{from_archived, LmdbGs, RkvGs, StorageMode};
+    use gs::migrate::migrate;
+    use gs::safe_mode::SafeModeBackend;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use tempfile::tempdir;
+
+    #[test]
+    fn migrate_copies_every_key_to_a_different_backend() {
+        let src_dir = tempdir().unwrap();
+        let dst_dir = tempdir().unwrap();
+        let src = LmdbGs::new(src_dir.path()).unwrap();
+        let dst = RkvGs::<SafeModeBackend>::new(dst_dir.path()).unwrap();
+        // `src` is shared by every proptest case and `migrate` copies all of
+        // it, so the expected count is every distinct key written so far.
+        let written: RefCell<HashSet<Key>> = RefCell::new(HashSet::new());
+
+        proptest!(|(ks in prop::collection::hash_set(key_arb(), 5), v in value_arb())| {
+            for k in &ks {
+                src.write_single(*k, &v).unwrap();
+                written.borrow_mut().insert(*k);
+            }
+
+            let migrated = migrate(src_dir.path(), StorageMode::ToBytes, &dst, false).unwrap();
+            prop_assert_eq!(migrated, written.borrow().len());
+            for k in &ks {
+                prop_assert_eq!(dst.read(k), Ok(v.clone()));
+            }
+        });
+    }
+
+    #[test]
+    fn dry_run_leaves_destination_untouched() {
+        let src_dir = tempdir().unwrap();
+        let dst_dir = tempdir().unwrap();
+        let src = LmdbGs::new(src_dir.path()).unwrap();
+        let dst = RkvGs::<SafeModeBackend>::new(dst_dir.path()).unwrap();
+        // `src` is shared by every proptest case, so the count covers every
+        // distinct key written so far.
+        let written: RefCell<HashSet<Key>> = RefCell::new(HashSet::new());
+
+        proptest!(|(k in key_arb(), v in value_arb())| {
+            src.write_single(k, &v).unwrap();
+            written.borrow_mut().insert(k);
+
+            let migrated = migrate(src_dir.path(), StorageMode::ToBytes, &dst, true).unwrap();
+            prop_assert_eq!(migrated, written.borrow().len());
+            prop_assert_eq!(dst.read(&k), Err(Error::KeyNotFound { key: k }));
+        });
+    }
+
+    #[test]
+    fn migrate_commits_more_than_one_batch() {
+        let src_dir = tempdir().unwrap();
+        let dst_dir = tempdir().unwrap();
+        let src = LmdbGs::new(src_dir.path()).unwrap();
+        let dst = RkvGs::<SafeModeBackend>::new(dst_dir.path()).unwrap();
+
+        // `BATCH_SIZE` is 1_000; this exercises the loop committing a batch
+        // mid-iteration and again for the trailing partial batch, not just a
+        // single commit at the end.
+        proptest!(ProptestConfig::with_cases(1), |(ks in prop::collection::hash_set(key_arb(), 1_500), v in value_arb())| {
+            for k in &ks {
+                src.write_single(*k, &v).unwrap();
+            }
+
+            let migrated = migrate(src_dir.path(), StorageMode::ToBytes, &dst, false).unwrap();
+            prop_assert_eq!(migrated, ks.len());
+            for k in &ks {
+                prop_assert_eq!(dst.read(k), Ok(v.clone()));
+            }
+        });
+    }
+
+    #[test]
+    fn migrate_into_rkyv_destination_is_read_archived_afterwards() {
+        let src_dir = tempdir().unwrap();
+        let dst_dir = tempdir().unwrap();
+        let src = LmdbGs::new(src_dir.path()).unwrap();
+        let dst =
+            RkvGs::<SafeModeBackend>::new_with_mode(dst_dir.path(), StorageMode::Rkyv).unwrap();
+
+        proptest!(|(k in key_arb(), v in value_arb())| {
+            src.write_single(k, &v).unwrap();
+
+            migrate(src_dir.path(), StorageMode::ToBytes, &dst, false).unwrap();
+
+            let handle = dst.read_archived(&k).unwrap();
+            prop_assert_eq!(from_archived(handle.get()), v);
+        });
+    }
+
+    #[test]
+    fn migrate_from_rkyv_source_reads_its_values() {
+        let src_dir = tempdir().unwrap();
+        let dst_dir = tempdir().unwrap();
+        let src = LmdbGs::new_with_mode(src_dir.path(), StorageMode::Rkyv).unwrap();
+        let dst = RkvGs::<SafeModeBackend>::new(dst_dir.path()).unwrap();
+        // `src` is shared by every proptest case, so the count covers every
+        // distinct key written so far.
+        let written: RefCell<HashSet<Key>> = RefCell::new(HashSet::new());
+
+        proptest!(|(k in key_arb(), v in value_arb())| {
+            src.write_single(k, &v).unwrap();
+            written.borrow_mut().insert(k);
+
+            let migrated = migrate(src_dir.path(), StorageMode::Rkyv, &dst, false).unwrap();
+            prop_assert_eq!(migrated, written.borrow().len());
+            prop_assert_eq!(dst.read(&k), Ok(v));
+        });
+    }
+}
@@ -0,0 +1,59 @@
+//! A RocksDB-backed `GlobalStateBackend`, for validator workloads LMDB's
+//! fixed map size and single-writer model don't suit.
+
+use common::bytesrepr::ToBytes;
+use common::key::Key;
+use error::Error;
+use gs::backend::GlobalStateBackend;
+use gs::lmdb::RkvGs;
+use rocksdb::{WriteBatch, DB};
+use std::path::Path;
+
+pub struct RocksDbBackend {
+    db: DB,
+}
+
+impl GlobalStateBackend for RocksDbBackend {
+    fn open(path: &Path) -> Result<RocksDbBackend, Error> {
+        let db = DB::open_default(path).map_err(|_| Error::RkvError)?;
+        Ok(RocksDbBackend { db })
+    }
+
+    fn read(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        self.db
+            .get(key.to_bytes())
+            .map_err(|_| Error::RkvError)
+    }
+
+    fn write<I>(&self, batch: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = (Key, Vec<u8>)>,
+    {
+        let mut write_batch = WriteBatch::default();
+        for (key, bytes) in batch {
+            write_batch.put(key.to_bytes(), bytes);
+        }
+        // `DB::write` applies the whole batch atomically and never
+        // partially; on error nothing in it has landed, giving us the same
+        // commit-or-abort semantics as the `rkv` backend's write txn.
+        self.db.write(write_batch).map_err(|_| Error::RkvError)
+    }
+}
+
+/// `RkvGs` specialized to the RocksDB backend, analogous to `LmdbGs` for
+/// `RkvBackend`. Implements the same `GlobalState`, `DbReader` and
+/// `tracking_copy` surface via the shared generic impls on `RkvGs<B>`, so
+/// callers pick a backend at construction time (`RocksDbGs::new(path)`)
+/// instead of hard-coding LMDB.
+pub type RocksDbGs = RkvGs<RocksDbBackend>;
+
+#[cfg(test)]
+mod tests {
+    use gs::rocksdb::RocksDbBackend;
+    use gs::test_support::rw_roundtrip;
+
+    #[test]
+    fn rocksdb_rw() {
+        rw_roundtrip::<RocksDbBackend>();
+    }
+}
@@ -0,0 +1,90 @@
+//! A pure-Rust `GlobalStateBackend` that needs no native LMDB build.
+//!
+//! Unlike `RkvBackend`, there is no MVCC here: the whole store lives in
+//! memory and is rewritten to disk as one file on every commit. Fine for the
+//! sizes this mode is meant for (tests, tooling); not a replacement for
+//! `RkvBackend` at validator scale.
+
+use common::bytesrepr::{deserialize, ToBytes};
+use common::key::Key;
+use error::Error;
+use gs::backend::GlobalStateBackend;
+use gs::lmdb::RkvGs;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// On-disk file name for the safe-mode store's flat key/value dump.
+const STORE_FILE: &str = "safe_mode.db";
+
+pub struct SafeModeBackend {
+    path: PathBuf,
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+/// [`RkvGs`] specialized to the pure-Rust safe-mode backend, analogous to
+/// `LmdbGs` for `RkvBackend`.
+pub type SafeModeGs = RkvGs<SafeModeBackend>;
+
+impl SafeModeBackend {
+    fn store_path(path: &Path) -> PathBuf {
+        path.join(STORE_FILE)
+    }
+
+    fn load(path: &Path) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, Error> {
+        let store_path = Self::store_path(path);
+        if !store_path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let mut bytes = Vec::new();
+        fs::File::open(&store_path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(|_| Error::RkvError)?;
+        deserialize(&bytes)
+    }
+
+    /// Rewrite the whole store to a temp file and rename it into place, so a
+    /// crash mid-write never leaves a half-written store behind. Stands in
+    /// for LMDB's commit/abort for this backend.
+    fn flush(&self, entries: &BTreeMap<Vec<u8>, Vec<u8>>) -> Result<(), Error> {
+        fs::create_dir_all(&self.path).map_err(|_| Error::RkvError)?;
+        let tmp_path = self.path.join(format!("{}.tmp", STORE_FILE));
+        let bytes = entries.to_bytes();
+        {
+            let mut f = fs::File::create(&tmp_path).map_err(|_| Error::RkvError)?;
+            f.write_all(&bytes).map_err(|_| Error::RkvError)?;
+        }
+        fs::rename(&tmp_path, Self::store_path(&self.path)).map_err(|_| Error::RkvError)
+    }
+}
+
+impl GlobalStateBackend for SafeModeBackend {
+    fn open(path: &Path) -> Result<SafeModeBackend, Error> {
+        let entries = Self::load(path)?;
+        Ok(SafeModeBackend {
+            path: path.to_path_buf(),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    fn read(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        let entries = self.entries.read().map_err(|_| Error::RkvError)?;
+        Ok(entries.get(&key.to_bytes()).cloned())
+    }
+
+    fn write<I>(&self, batch: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = (Key, Vec<u8>)>,
+    {
+        let mut entries = self.entries.write().map_err(|_| Error::RkvError)?;
+        let mut next = entries.clone();
+        for (key, bytes) in batch {
+            next.insert(key.to_bytes(), bytes);
+        }
+        self.flush(&next)?;
+        *entries = next;
+        Ok(())
+    }
+}
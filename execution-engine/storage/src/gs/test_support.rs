@@ -0,0 +1,23 @@
+//! Shared `#[cfg(test)]` fixtures for `gs::*`'s backend proptests, so each
+//! new `GlobalStateBackend` impl (`SafeModeBackend`, `RocksDbBackend`, ...)
+//! exercises the same round trip instead of re-typing it.
+
+use gens::gens::*;
+use gs::backend::GlobalStateBackend;
+use gs::lmdb::RkvGs;
+use tempfile::tempdir;
+
+/// Write then read back every `(Key, Value)` proptest produces, against a
+/// freshly opened store for backend `B`.
+pub(crate) fn rw_roundtrip<B: GlobalStateBackend>() {
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path();
+    let gs = RkvGs::<B>::new(&path).unwrap();
+
+    proptest!(|(k in key_arb(), v in value_arb())| {
+      let write = gs.write_single(k, &v);
+      let read = gs.read(&k);
+      assert_matches!(write, Ok(_));
+      prop_assert_eq!(read, Ok(v.clone()));
+    });
+}